@@ -0,0 +1,262 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+//! A JSON-RPC control server driven over stdin/stdout, reusing the same
+//! plumbing as the singleton socket so this CLI can be driven
+//! programmatically by a parent process (e.g. a WSL or embedded launcher)
+//! instead of only by an interactive terminal attaching to the singleton.
+
+use std::{
+	process::Stdio,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+};
+
+use anyhow::{bail, Context, Result};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+	json_rpc::{new_json_rpc, start_json_rpc, MethodBuilder},
+	log,
+	util::sync::Barrier,
+};
+
+use super::shutdown_signal::ShutdownSignal;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HandshakeParams {
+	/// Base64-encoded HMAC-SHA256 of the challenge printed to stdout when
+	/// the server started, keyed with the secret the parent process was
+	/// given out-of-band when it spawned us.
+	pub signature: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InstallLocalParams {
+	/// Path to a server archive on disk; it is unpacked into a directory
+	/// beside it named after the archive without its suffix.
+	pub archive_path: String,
+	/// Port to boot the server on; 0 picks any free port.
+	pub port: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InstallLocalResult {
+	pub port: u16,
+	pub pid: u32,
+}
+
+pub struct ControlServerArgs {
+	pub log: log::Logger,
+	pub shutdown: Barrier<ShutdownSignal>,
+	/// Secret shared out-of-band with the parent process that spawned us
+	/// (e.g. passed via an environment variable it controls), used to key
+	/// the handshake HMAC. This must never be sent over our own stdio.
+	pub shared_secret: Vec<u8>,
+}
+
+struct ControlServerContext {
+	log: log::Logger,
+	authenticated: Arc<AtomicBool>,
+	shared_secret: Vec<u8>,
+	challenge: Vec<u8>,
+}
+
+/// Starts a JSON-RPC control server over stdin/stdout. Prints a base64
+/// challenge on the first line of stdout before reading any commands; the
+/// caller must round-trip an HMAC of it, keyed with `shared_secret`, via
+/// `handshake` before any other registered command is dispatched. Blocks
+/// until stdin closes or a shutdown is requested.
+pub async fn start_control_server(args: ControlServerArgs) {
+	let mut rpc = new_json_rpc();
+	let (_msg_tx, msg_rx) = tokio::sync::mpsc::unbounded_channel();
+
+	let mut challenge = vec![0u8; 32];
+	rand::thread_rng().fill_bytes(&mut challenge);
+	println!("{}", base64::encode(&challenge));
+
+	let mut rpc = rpc.methods(ControlServerContext {
+		log: args.log.clone(),
+		authenticated: Arc::new(AtomicBool::new(false)),
+		shared_secret: args.shared_secret,
+		challenge,
+	});
+
+	rpc.register_sync("handshake", |params: HandshakeParams, c| {
+		if verify_signature(&c.shared_secret, &c.challenge, &params.signature) {
+			c.authenticated.store(true, Ordering::SeqCst);
+			Ok(())
+		} else {
+			bail!("signature did not match the session challenge");
+		}
+	});
+
+	register_authenticated(&mut rpc, "install_local", |params: InstallLocalParams, c| {
+		install_local(&c.log, params)
+	});
+
+	let (read, write) = (tokio::io::stdin(), tokio::io::stdout());
+	let _ = start_json_rpc(rpc.build(args.log), read, write, msg_rx, args.shutdown).await;
+}
+
+/// Registers `method` so it is rejected with an error until `handshake` has
+/// succeeded on this session. Every authenticated command should be added
+/// through this helper rather than checking `authenticated` itself, so the
+/// guard can't be forgotten as more commands are registered.
+fn register_authenticated<P, R>(
+	rpc: &mut MethodBuilder<ControlServerContext>,
+	method: &'static str,
+	handler: impl Fn(P, &ControlServerContext) -> Result<R> + Send + Sync + 'static,
+) where
+	P: DeserializeOwned + Send + 'static,
+	R: Serialize + Send + 'static,
+{
+	rpc.register_sync(method, move |params: P, c: &ControlServerContext| {
+		if !c.authenticated.load(Ordering::SeqCst) {
+			bail!("session has not completed the handshake");
+		}
+		handler(params, c)
+	});
+}
+
+fn install_local(log: &log::Logger, params: InstallLocalParams) -> Result<InstallLocalResult> {
+	let archive_path = std::path::Path::new(&params.archive_path);
+	if !archive_path.exists() {
+		bail!("archive path {} does not exist", params.archive_path);
+	}
+
+	let install_dir = install_dir_for_archive(archive_path);
+	debug!(
+		log,
+		"unpacking {} to {}",
+		params.archive_path,
+		install_dir.display()
+	);
+	crate::util::tar::decompress_tarball(archive_path, &install_dir)
+		.context("failed to unpack server archive")?;
+
+	let port = if params.port == 0 {
+		std::net::TcpListener::bind(("127.0.0.1", 0))?
+			.local_addr()?
+			.port()
+	} else {
+		params.port
+	};
+
+	let server_bin = install_dir.join("bin").join("code-server");
+	let mut child = std::process::Command::new(&server_bin)
+		.arg("--port")
+		.arg(port.to_string())
+		// The child must not inherit our stdio: it carries the control
+		// server's JSON-RPC handshake and request/response traffic, and any
+		// startup output the server wrote to stdout would corrupt it.
+		.stdin(Stdio::null())
+		.stdout(Stdio::null())
+		.stderr(Stdio::null())
+		.spawn()
+		.with_context(|| format!("failed to start {}", server_bin.display()))?;
+
+	let pid = child.id();
+	let log = log.clone();
+	std::thread::spawn(move || match child.wait() {
+		Ok(status) => debug!(log, "code-server (pid {}) exited: {}", pid, status),
+		Err(e) => debug!(log, "failed to wait on code-server (pid {}): {}", pid, e),
+	});
+
+	Ok(InstallLocalResult { port, pid })
+}
+
+/// Strips a known archive suffix (`.tar.gz`, `.tgz`, `.tar`, `.zip`) from
+/// `archive_path`'s file name to get the directory to unpack it into.
+/// `Path::with_extension` only strips the last extension, so it would turn
+/// `foo.tar.gz` into `foo.tar` rather than `foo`.
+fn install_dir_for_archive(archive_path: &std::path::Path) -> std::path::PathBuf {
+	let name = archive_path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+	let stem = [".tar.gz", ".tgz", ".tar", ".zip"]
+		.iter()
+		.find_map(|suffix| name.strip_suffix(suffix))
+		.unwrap_or(name);
+	archive_path.with_file_name(stem)
+}
+
+/// Verifies `signature` is the base64-encoded HMAC-SHA256 of `challenge`
+/// keyed with `secret`. Plain equality against the challenge would let
+/// anyone who can read our stdout (the exact thing this handshake guards
+/// against) "sign" by echoing it straight back, so the caller must instead
+/// prove it holds `secret`, which is never written to our stdio.
+fn verify_signature(secret: &[u8], challenge: &[u8], signature: &str) -> bool {
+	let decoded = match base64::decode(signature) {
+		Ok(d) => d,
+		Err(_) => return false,
+	};
+	let mut mac = match HmacSha256::new_from_slice(secret) {
+		Ok(m) => m,
+		Err(_) => return false,
+	};
+	mac.update(challenge);
+	mac.verify_slice(&decoded).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn sign(secret: &[u8], challenge: &[u8]) -> String {
+		let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+		mac.update(challenge);
+		base64::encode(mac.finalize().into_bytes())
+	}
+
+	#[test]
+	fn accepts_a_correctly_signed_challenge() {
+		let secret = b"shared-secret";
+		let challenge = b"challenge-bytes";
+		assert!(verify_signature(secret, challenge, &sign(secret, challenge)));
+	}
+
+	#[test]
+	fn rejects_the_challenge_echoed_back_unsigned() {
+		let secret = b"shared-secret";
+		let challenge = b"challenge-bytes";
+		// The attack this guards against: a process that can only read our
+		// stdout, and so only knows the challenge, not the shared secret.
+		assert!(!verify_signature(
+			secret,
+			challenge,
+			&base64::encode(challenge)
+		));
+	}
+
+	#[test]
+	fn rejects_a_signature_made_with_the_wrong_secret() {
+		let challenge = b"challenge-bytes";
+		let signature = sign(b"attacker-guess", challenge);
+		assert!(!verify_signature(b"shared-secret", challenge, &signature));
+	}
+
+	#[test]
+	fn install_dir_strips_the_full_archive_suffix() {
+		let cases = [
+			("/tmp/foo.tar.gz", "/tmp/foo"),
+			("/tmp/foo.tgz", "/tmp/foo"),
+			("/tmp/foo.tar", "/tmp/foo"),
+			("/tmp/foo.zip", "/tmp/foo"),
+			("/tmp/foo", "/tmp/foo"),
+		];
+		for (archive, expected) in cases {
+			assert_eq!(
+				install_dir_for_archive(std::path::Path::new(archive)),
+				std::path::PathBuf::from(expected),
+			);
+		}
+	}
+}
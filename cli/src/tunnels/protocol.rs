@@ -0,0 +1,71 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+//! Types shared between the singleton server and the clients that attach to
+//! it over the JSON-RPC socket.
+
+use serde::{Deserialize, Serialize};
+
+/// Params/result type for JSON-RPC methods that carry no data.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct EmptyObject {}
+
+pub mod singleton {
+	use super::*;
+
+	#[derive(Serialize, Deserialize, Debug, Clone)]
+	pub struct LogMessageOwned {
+		pub level: Option<::log::Level>,
+		pub prefix: String,
+		pub message: String,
+	}
+
+	/// Response to a `status` query against a running singleton.
+	#[derive(Serialize, Deserialize, Debug, Clone)]
+	pub struct StatusOutput {
+		/// Name of the tunnel the singleton is currently serving, if any.
+		pub tunnel_name: Option<String>,
+		/// Whether the tunnel is currently connected to the relay.
+		pub is_connected: bool,
+		/// PID of the process serving the tunnel.
+		pub serving_pid: u32,
+	}
+
+	/// Why a singleton's tunnel is going away, reported to attached clients
+	/// before the pipe closes so they can tell an intentional shutdown from
+	/// a crash.
+	#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+	pub enum ShutdownReason {
+		/// The user pressed `x` to stop the tunnel.
+		UserRequested,
+		/// The host service is restarting (e.g. a Windows service restart).
+		ServiceRestarting,
+		/// The process that spawned the singleton has exited.
+		ParentProcessExited,
+		/// An update to the CLI is pending and the tunnel is being recycled.
+		UpdatePending,
+		/// The tunnel exited due to an unrecoverable error.
+		FatalError,
+	}
+
+	impl std::fmt::Display for ShutdownReason {
+		fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+			f.write_str(match self {
+				ShutdownReason::UserRequested => "user requested the tunnel to stop",
+				ShutdownReason::ServiceRestarting => "the service is restarting",
+				ShutdownReason::ParentProcessExited => "the parent process exited",
+				ShutdownReason::UpdatePending => "an update is pending",
+				ShutdownReason::FatalError => "a fatal error occurred",
+			})
+		}
+	}
+
+	/// Params for the `shutdown_reason` notification a singleton sends to its
+	/// attached clients before closing the pipe.
+	#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+	pub struct ShutdownSignalParams {
+		pub reason: ShutdownReason,
+	}
+}
@@ -0,0 +1,27 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+//! The signal threaded through a `Barrier` to tell a JSON-RPC event loop to
+//! wind down, and why.
+
+use super::protocol::singleton::ShutdownReason;
+
+/// Reason a JSON-RPC loop driven by this crate is being torn down.
+#[derive(Debug, Clone, Copy)]
+pub enum ShutdownSignal {
+	/// The local process is exiting (e.g. Ctrl+C) and wants the loop to stop.
+	CtrlC,
+	/// The remote side reported a reason the tunnel is going away.
+	Remote(ShutdownReason),
+}
+
+impl std::fmt::Display for ShutdownSignal {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ShutdownSignal::CtrlC => write!(f, "ctrl+c"),
+			ShutdownSignal::Remote(reason) => write!(f, "{}", reason),
+		}
+	}
+}
@@ -6,11 +6,13 @@
 use std::{
 	sync::{
 		atomic::{AtomicBool, Ordering},
-		Arc,
+		Arc, Mutex, Once,
 	},
 	thread,
+	time::Duration,
 };
 
+use once_cell::sync::Lazy;
 use tokio::sync::mpsc;
 
 use crate::{
@@ -18,10 +20,44 @@ use crate::{
 	json_rpc::{new_json_rpc, start_json_rpc},
 	log,
 	tunnels::protocol::EmptyObject,
-	util::sync::Barrier,
+	util::{is_interactive::IS_INTERACTIVE_CLI, sync::Barrier},
 };
 
-use super::{protocol, shutdown_signal::ShutdownSignal};
+use super::{
+	protocol,
+	protocol::singleton::{ShutdownReason, StatusOutput},
+	shutdown_signal::ShutdownSignal,
+};
+
+/// Instructions printed once a client attaches to a running tunnel,
+/// describing the keys it can send to the singleton.
+const CONTROL_INSTRUCTIONS: &str = "Commands:\n  x + Enter: stop the tunnel\n  r + Enter: restart the tunnel";
+
+/// Appended to `CONTROL_INSTRUCTIONS` when attached to an interactive
+/// terminal, where detaching without shutting down the tunnel is possible.
+const DETACH_INSTRUCTIONS: &str = "  Ctrl+C: detach (tunnel keeps running)";
+
+/// The shutdown barrier for whichever singleton client session is currently
+/// attached in this process, if any.
+static CURRENT_SHUTDOWN: Lazy<Mutex<Option<Barrier<ShutdownSignal>>>> = Lazy::new(|| Mutex::new(None));
+
+/// `ctrlc::set_handler` may only be installed once per process; a second
+/// call errors and leaves the first handler in place. So rather than
+/// re-registering per `start_singleton_client` call (which would leave a
+/// stale closure pointing at an already-consumed `Barrier` once the process
+/// re-enters this function, e.g. after a restart), install a single
+/// process-wide handler the first time and have it forward to whatever
+/// session is currently attached via `CURRENT_SHUTDOWN`.
+fn ensure_ctrlc_handler_installed() {
+	static INSTALLED: Once = Once::new();
+	INSTALLED.call_once(|| {
+		let _ = ctrlc::set_handler(|| {
+			if let Some(shutdown) = CURRENT_SHUTDOWN.lock().unwrap().as_ref() {
+				shutdown.open(ShutdownSignal::CtrlC);
+			}
+		});
+	});
+}
 
 pub struct SingletonClientArgs {
 	pub log: log::Logger,
@@ -29,36 +65,119 @@ pub struct SingletonClientArgs {
 	pub shutdown: Barrier<ShutdownSignal>,
 }
 
+/// Timeout waiting for a `status` response from a singleton that may be
+/// wedged.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Makes a single `status` call against a running singleton and returns its
+/// response, without attaching interactively. Returns `None` if the
+/// singleton did not respond within `STATUS_TIMEOUT`.
+pub async fn query_singleton_status(args: SingletonClientArgs) -> Option<StatusOutput> {
+	let mut rpc = new_json_rpc();
+	let (msg_tx, msg_rx) = mpsc::unbounded_channel();
+	let caller = rpc.get_caller(msg_tx);
+
+	let rpc = rpc.methods(());
+	let (read, write) = socket_stream_split(args.stream);
+	let _handle = tokio::spawn(start_json_rpc(
+		rpc.build(args.log),
+		read,
+		write,
+		msg_rx,
+		args.shutdown,
+	));
+
+	tokio::time::timeout(STATUS_TIMEOUT, caller.call::<_, StatusOutput>("status", EmptyObject {}))
+		.await
+		.ok()?
+		.ok()
+}
+
+/// Backs the `tunnel status [--json]` subcommand: queries the running
+/// singleton and prints its status, or reports that none is running /
+/// reachable. Returns the process exit code.
+pub async fn run_status_command(args: SingletonClientArgs, json: bool) -> i32 {
+	match query_singleton_status(args).await {
+		Some(status) if json => {
+			println!("{}", serde_json::to_string(&status).unwrap());
+			0
+		}
+		Some(status) => {
+			println!(
+				"Tunnel: {}\nConnected: {}\nPID: {}",
+				status.tunnel_name.as_deref().unwrap_or("(none)"),
+				status.is_connected,
+				status.serving_pid
+			);
+			0
+		}
+		None => {
+			eprintln!("No running tunnel responded to the status query.");
+			1
+		}
+	}
+}
+
 struct SingletonServerContext {
 	log: log::Logger,
 	exit_entirely: Arc<AtomicBool>,
+	/// Set once the server has replayed its backlog and sent `log_done`, so
+	/// the rest of the process can tell historical output from live output.
+	caught_up: Arc<AtomicBool>,
+	/// "Connecting..." spinner shown until `caught_up` flips, if this is an
+	/// interactive session.
+	spinner: Arc<Mutex<Option<indicatif::ProgressBar>>>,
+	/// Why the singleton reported the tunnel is going away, if it told us
+	/// before the pipe closed.
+	shutdown_reason: Arc<Mutex<Option<ShutdownReason>>>,
 }
 
-/// Serves a client singleton. Returns true if the process should exit after
-/// this returns, instead of trying to start a tunnel.
-pub async fn start_singleton_client(args: SingletonClientArgs) -> bool {
+/// Outcome of an attached singleton client session.
+pub struct SingletonClientResult {
+	/// Whether the process should exit after this returns, instead of
+	/// trying to start a tunnel.
+	pub exit_entirely: bool,
+	/// Why the tunnel went away, if the singleton reported a reason before
+	/// the pipe closed. `None` means the connection was simply lost.
+	pub shutdown_reason: Option<ShutdownReason>,
+}
+
+/// Serves a client singleton, returning once the connection to it closes.
+pub async fn start_singleton_client(args: SingletonClientArgs) -> SingletonClientResult {
 	let mut rpc = new_json_rpc();
 	let (msg_tx, msg_rx) = mpsc::unbounded_channel();
 	let exit_entirely = Arc::new(AtomicBool::new(false));
+	let caught_up = Arc::new(AtomicBool::new(false));
+	let shutdown_reason = Arc::new(Mutex::new(None));
+	let final_log = args.log.clone();
 
 	debug!(
 		args.log,
 		"An existing tunnel is running on this machine, connecting to it..."
 	);
 
+	let spinner = if *IS_INTERACTIVE_CLI {
+		println!("{}\n{}", CONTROL_INSTRUCTIONS, DETACH_INSTRUCTIONS);
+		*CURRENT_SHUTDOWN.lock().unwrap() = Some(args.shutdown.clone());
+		ensure_ctrlc_handler_installed();
+		let s = indicatif::ProgressBar::new_spinner();
+		s.set_message("Connecting...");
+		s.enable_steady_tick(Duration::from_millis(100));
+		Some(s)
+	} else {
+		println!("{}", CONTROL_INSTRUCTIONS);
+		None
+	};
+	let spinner = Arc::new(Mutex::new(spinner));
+
 	let stdin_handle = rpc.get_caller(msg_tx);
 	thread::spawn(move || {
-		let term = console::Term::stderr();
-		loop {
-			match term.read_key() {
-				Ok(console::Key::Char('x')) => {
-					stdin_handle.notify("shutdown", EmptyObject {});
-				}
-				Ok(console::Key::Char('r')) => {
-					stdin_handle.notify("restart", EmptyObject {});
-				}
-				Err(_) => return, // EOF or not a tty
-				_ => {}
+		for line in std::io::stdin().lines() {
+			match line.as_deref().map(str::trim) {
+				Ok("x") => stdin_handle.notify("shutdown", EmptyObject {}),
+				Ok("r") => stdin_handle.notify("restart", EmptyObject {}),
+				Ok(_) => continue,
+				Err(_) => return, // EOF or stdin closed
 			}
 		}
 	});
@@ -66,6 +185,9 @@ pub async fn start_singleton_client(args: SingletonClientArgs) -> bool {
 	let mut rpc = rpc.methods(SingletonServerContext {
 		log: args.log.clone(),
 		exit_entirely: exit_entirely.clone(),
+		caught_up: caught_up.clone(),
+		spinner: spinner.clone(),
+		shutdown_reason: shutdown_reason.clone(),
 	});
 
 	rpc.register_sync("shutdown", |_: EmptyObject, c| {
@@ -73,6 +195,17 @@ pub async fn start_singleton_client(args: SingletonClientArgs) -> bool {
 		Ok(())
 	});
 
+	// Sent by the singleton before it closes the pipe, so we can tell an
+	// intentional shutdown (restart, update) apart from a crash.
+	rpc.register_sync(
+		"shutdown_reason",
+		|params: protocol::singleton::ShutdownSignalParams, c| {
+			c.shutdown_reason.lock().unwrap().replace(params.reason);
+			c.exit_entirely.store(true, Ordering::SeqCst);
+			Ok(())
+		},
+	);
+
 	rpc.register_sync("log", |log: protocol::singleton::LogMessageOwned, c| {
 		match log.level {
 			Some(level) => c.log.emit(level, &format!("{}{}", log.prefix, log.message)),
@@ -81,8 +214,55 @@ pub async fn start_singleton_client(args: SingletonClientArgs) -> bool {
 		Ok(())
 	});
 
+	// Sent once the server has replayed its backlog of buffered records, so
+	// we know subsequent `log` notifications reflect real-time activity.
+	rpc.register_sync("log_done", |_: EmptyObject, c| {
+		c.caught_up.store(true, Ordering::SeqCst);
+		if let Some(s) = c.spinner.lock().unwrap().take() {
+			s.finish_and_clear();
+		}
+		Ok(())
+	});
+
 	let (read, write) = socket_stream_split(args.stream);
-	let _ = start_json_rpc(rpc.build(args.log), read, write, msg_rx, args.shutdown).await;
+	let loop_signal = start_json_rpc(rpc.build(args.log), read, write, msg_rx, args.shutdown).await;
+
+	let reason = shutdown_reason.lock().unwrap().take();
+	match (loop_signal, reason) {
+		(Ok(ShutdownSignal::CtrlC), _) => {
+			log!(final_log, "Detached; the tunnel keeps running in the background.")
+		}
+		(_, Some(reason)) => log!(final_log, "Tunnel stopped: {}", reason),
+		_ => debug!(final_log, "Connection to the singleton was lost."),
+	}
+
+	SingletonClientResult {
+		exit_entirely: exit_entirely.load(Ordering::SeqCst),
+		shutdown_reason: reason,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::sync::Barrier;
+
+	#[tokio::test(start_paused = true)]
+	async fn query_singleton_status_times_out_when_server_is_wedged() {
+		// Nothing reads the other end, so the `status` call never gets a
+		// response and the call must resolve via STATUS_TIMEOUT instead of
+		// hanging forever.
+		let (client_side, _server_side) = tokio::io::duplex(1024);
+		let args = SingletonClientArgs {
+			log: log::Logger::test(),
+			stream: AsyncPipe::from(client_side),
+			shutdown: Barrier::new(),
+		};
+
+		let started = tokio::time::Instant::now();
+		let result = query_singleton_status(args).await;
 
-	exit_entirely.load(Ordering::SeqCst)
+		assert!(result.is_none());
+		assert!(tokio::time::Instant::now() - started >= STATUS_TIMEOUT);
+	}
 }
@@ -0,0 +1,147 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+//! The long-running singleton process's side of the JSON-RPC protocol
+//! defined alongside it in `singleton_client.rs`: the methods it answers
+//! for each attached client, and the log backfill it sends to a client
+//! that has just attached.
+
+use std::{
+	collections::VecDeque,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use crate::json_rpc::MethodBuilder;
+
+use super::protocol::{
+	singleton::{LogMessageOwned, StatusOutput},
+	EmptyObject,
+};
+
+/// Status the running singleton exposes to `status` queries, shared across
+/// however many clients are currently attached.
+pub struct SingletonServerStatus {
+	pub tunnel_name: Mutex<Option<String>>,
+	pub is_connected: AtomicBool,
+}
+
+impl SingletonServerStatus {
+	pub fn new() -> Self {
+		Self {
+			tunnel_name: Mutex::new(None),
+			is_connected: AtomicBool::new(false),
+		}
+	}
+}
+
+impl Default for SingletonServerStatus {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Registers the `status` method answered on the singleton's side of the
+/// socket, the counterpart `query_singleton_status` calls for a one-shot,
+/// non-attaching status check.
+pub fn register_status<C>(rpc: &mut MethodBuilder<C>)
+where
+	C: AsRef<Arc<SingletonServerStatus>> + Send + Sync + 'static,
+{
+	rpc.register_sync("status", |_: EmptyObject, c: &C| {
+		let status = c.as_ref();
+		Ok(StatusOutput {
+			tunnel_name: status.tunnel_name.lock().unwrap().clone(),
+			is_connected: status.is_connected.load(Ordering::SeqCst),
+			serving_pid: std::process::id(),
+		})
+	});
+}
+
+/// Bounded backlog of recent `log` records, shared across however many
+/// clients are currently attached, so a client that attaches mid-session
+/// isn't stuck looking at a blank screen until the next log line arrives.
+/// Oldest records are dropped once `capacity` is exceeded.
+pub struct LogRingBuffer {
+	capacity: usize,
+	records: Mutex<VecDeque<LogMessageOwned>>,
+}
+
+impl LogRingBuffer {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			capacity,
+			records: Mutex::new(VecDeque::with_capacity(capacity)),
+		}
+	}
+
+	/// Appends a record that was just logged live, trimming the oldest
+	/// entry if the buffer is at capacity.
+	pub fn push(&self, record: LogMessageOwned) {
+		let mut records = self.records.lock().unwrap();
+		if records.len() >= self.capacity {
+			records.pop_front();
+		}
+		records.push_back(record);
+	}
+
+	/// A point-in-time copy of the buffered records, oldest first. Safe to
+	/// call while `push` is running concurrently on other threads; the
+	/// snapshot will simply include or exclude the racing record.
+	fn snapshot(&self) -> Vec<LogMessageOwned> {
+		self.records.lock().unwrap().iter().cloned().collect()
+	}
+}
+
+/// Replays `buffer`'s backlog to a newly attached client via the same
+/// `log` notification used for live records, then sends `log_done` so the
+/// client can tell it has caught up to "live". `notify` should send a
+/// JSON-RPC notification to that one client; it's fine for records to keep
+/// being appended to `buffer` concurrently while this runs.
+pub fn replay_log_backfill(buffer: &LogRingBuffer, mut notify: impl FnMut(&str, serde_json::Value)) {
+	for record in buffer.snapshot() {
+		notify("log", serde_json::to_value(&record).expect("LogMessageOwned is always serializable"));
+	}
+	notify("log_done", serde_json::to_value(EmptyObject {}).expect("EmptyObject is always serializable"));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn msg(text: &str) -> LogMessageOwned {
+		LogMessageOwned {
+			level: None,
+			prefix: String::new(),
+			message: text.to_string(),
+		}
+	}
+
+	#[test]
+	fn drops_the_oldest_record_once_over_capacity() {
+		let buffer = LogRingBuffer::new(2);
+		buffer.push(msg("a"));
+		buffer.push(msg("b"));
+		buffer.push(msg("c"));
+
+		let snapshot = buffer.snapshot();
+		let messages: Vec<_> = snapshot.iter().map(|r| r.message.as_str()).collect();
+		assert_eq!(messages, vec!["b", "c"]);
+	}
+
+	#[test]
+	fn replay_sends_backlog_in_order_then_log_done() {
+		let buffer = LogRingBuffer::new(10);
+		buffer.push(msg("a"));
+		buffer.push(msg("b"));
+
+		let mut calls = Vec::new();
+		replay_log_backfill(&buffer, |method, _params| calls.push(method.to_string()));
+
+		assert_eq!(calls, vec!["log", "log", "log_done"]);
+	}
+}